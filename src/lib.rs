@@ -1,7 +1,8 @@
-use async_std::fs;
+use async_std::{fs, prelude::*};
+use indicatif::ProgressBar;
 use log::debug;
 use std::{
-    fmt, io,
+    cmp, fmt, io,
     io::prelude::*,
     path::{Path, PathBuf},
     str::FromStr,
@@ -12,6 +13,7 @@ use std::{
 pub struct TheiaPlugin {
     remote: TheiaPluginAPI,
     native: TheiaPluginLCL,
+    cache: TheiaPluginCache,
 }
 impl TheiaPlugin {
     pub fn new<P: AsRef<Path>, S: AsRef<str>>(
@@ -20,35 +22,255 @@ impl TheiaPlugin {
         download: S,  // find download url from json file
         theia_dir: P, // theia plugins dir
     ) -> Self {
+        let theia_dir = theia_dir.as_ref();
+        let cache_dir = theia_dir.parent().unwrap_or(theia_dir).join("cache");
         Self {
             remote: TheiaPluginAPI::new(regular, version, download),
             native: TheiaPluginLCL::new(theia_dir),
+            cache: TheiaPluginCache::new(cache_dir),
         }
     }
-    /// get installed version
-    pub async fn get_install_info<T: AsRef<str>>(&self, name: T) -> Result<Version, String> {
+    /// get installed version, `None` if the plugin is not installed
+    pub async fn get_install_info<T: AsRef<str>>(&self, name: T) -> Result<Option<Version>, String> {
         self.native.get_version(name).await
     }
-    /// get lastest version
-    pub async fn get_last_version<T: AsRef<str>>(&self, path: T) -> Result<(Version, String), String> {
-        self.remote.get_version(path).await
+    /// get the highest published version satisfying `require`
+    pub async fn get_last_version<T: AsRef<str>>(&self, path: T, require: &Requirement) -> Result<(Version, String), String> {
+        self.remote
+            .get_version(path)
+            .await?
+            .into_iter()
+            .filter(|(version, _)| require.matches(version))
+            .max_by(|a, b| a.0.cmp(&b.0))
+            .ok_or_else(|| "not find version matching requirement".to_owned())
     }
-    pub async fn upgrade<T: AsRef<str>>(&self, name: T, url: T) -> Result<(), String> {
-        let url = url.as_ref();
+    /// remove the installed directory for a plugin
+    pub async fn uninstall<T: AsRef<str>>(&self, name: T) -> Result<(), String> {
+        self.native.remove(name).await
+    }
+    /// list the plugin directory names currently installed under `target`
+    pub async fn installed(&self) -> Result<Vec<String>, String> {
+        self.native.installed().await
+    }
+    /// wipe the download cache
+    pub async fn clear_cache(&self) -> Result<(), String> {
+        self.cache.clear().await
+    }
+    pub async fn upgrade<T: AsRef<str>>(
+        &self,
+        name: T,
+        version: &Version,
+        url: T,
+        progress: Option<ProgressBar>,
+    ) -> Result<(), String> {
+        let result = self.upgrade_inner(name.as_ref(), version, url.as_ref(), progress.as_ref()).await;
+        // every exit must reach here: an unfinished bar blocks `MultiProgress::join()` forever
+        if let Some(bar) = &progress {
+            if result.is_err() {
+                bar.finish_and_clear();
+            }
+        }
+        result
+    }
+    async fn upgrade_inner(&self, name: &str, version: &Version, url: &str, progress: Option<&ProgressBar>) -> Result<(), String> {
+        let data = match self.cache.get(name, version).await {
+            Some(data) => {
+                debug!("{}: using cached {} archive", name, version);
+                if let Some(bar) = progress {
+                    bar.set_length(data.len() as u64);
+                    bar.set_position(data.len() as u64);
+                }
+                data
+            }
+            None => {
+                let data = self.download(url, progress).await?;
+                self.cache.store(name, version, &data).await?;
+                data
+            }
+        };
 
-        let data = surf::client()
-            .with(surf::middleware::Redirect::default())
-            .recv_bytes(surf::get(url))
+        self.native.installing(name, &data, progress)
+    }
+    /// stream the archive body, advancing `progress` (sized from `Content-Length` when present)
+    async fn download(&self, url: &str, progress: Option<&ProgressBar>) -> Result<Vec<u8>, String> {
+        use futures::AsyncReadExt;
+
+        let mut response = self
+            .remote
+            .client
+            .send(surf::get(url))
             .await
             .map_err(|e| format!("{}, {}", url, e))?;
 
-        self.native.installing(name, &data)
+        if let Some(bar) = progress {
+            // `response.len()` is the body's known length: `Content-Length`, or the decoded size
+            // when `DecompressMiddleware` already decoded a gzip/br body; it can be absent, so
+            // treat it as only a starting estimate and grow the bar below past it if needed
+            bar.set_length(response.len().unwrap_or(0) as u64);
+            bar.set_message("downloading");
+        }
+
+        let mut data = Vec::new();
+        let mut buffer = [0u8; 16 * 1024];
+        loop {
+            let read = response.read(&mut buffer).await.map_err(|e| format!("{}, {}", url, e))?;
+            if read == 0 {
+                break;
+            }
+            data.extend_from_slice(&buffer[..read]);
+            if let Some(bar) = progress {
+                if data.len() as u64 > bar.length() {
+                    bar.set_length(data.len() as u64);
+                }
+                bar.set_position(data.len() as u64);
+            }
+        }
+        Ok(data)
+    }
+}
+
+/// on-disk cache of downloaded VSIX archives, keyed by `{name}-{version}.vsix`
+#[derive(Clone)]
+struct TheiaPluginCache {
+    directory: PathBuf,
+}
+impl TheiaPluginCache {
+    fn new<T: AsRef<Path>>(directory: T) -> Self {
+        Self {
+            directory: directory.as_ref().into(),
+        }
+    }
+    fn paths(&self, name: &str, version: &Version) -> (PathBuf, PathBuf) {
+        let stem = self.directory.join(format!("{}-{}.vsix", name, version));
+        let manifest = stem.with_extension("vsix.manifest");
+        (stem, manifest)
+    }
+    /// return the cached archive bytes if present and its recorded hash/size still match
+    async fn get(&self, name: &str, version: &Version) -> Option<Vec<u8>> {
+        let (archive, manifest) = self.paths(name, version);
+
+        let manifest: CacheManifest = serde_json::from_str(&fs::read_to_string(&manifest).await.ok()?).ok()?;
+        let data = fs::read(&archive).await.ok()?;
+
+        if data.len() as u64 == manifest.size && sha256_hex(&data) == manifest.sha256 {
+            Some(data)
+        } else {
+            None
+        }
+    }
+    /// write the archive plus a sidecar manifest recording its hash and size
+    async fn store(&self, name: &str, version: &Version, data: &[u8]) -> Result<(), String> {
+        let (archive, manifest) = self.paths(name, version);
+
+        fs::create_dir_all(&self.directory)
+            .await
+            .map_err(|e| format!("{:?}: create cache dir, {}", self.directory, e))?;
+        fs::write(&archive, data)
+            .await
+            .map_err(|e| format!("{:?}: write cache entry, {}", archive, e))?;
+
+        let record = CacheManifest {
+            size: data.len() as u64,
+            sha256: sha256_hex(data),
+        };
+        let record = serde_json::to_string(&record).map_err(|e| e.to_string())?;
+        fs::write(&manifest, record)
+            .await
+            .map_err(|e| format!("{:?}: write cache manifest, {}", manifest, e))
+    }
+    /// remove every cached archive and manifest
+    async fn clear(&self) -> Result<(), String> {
+        match fs::remove_dir_all(&self.directory).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(format!("{:?}: clear cache, {}", self.directory, e)),
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CacheManifest {
+    size: u64,
+    sha256: String,
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// `surf::Client` shared by every outbound request: follows redirects and transparently
+/// negotiates/decodes a compressed response body
+fn build_client() -> surf::Client {
+    surf::Client::new()
+        .with(surf::middleware::Redirect::default())
+        .with(DecompressMiddleware)
+}
+
+/// sends `Accept-Encoding` and decodes `Content-Encoding: gzip`/`br` responses before
+/// they reach `recv_bytes`/the streaming reader; unknown or malformed encodings are left as-is.
+/// decoding buffers the whole body, so a compressed download's progress bar jumps once the
+/// (small) transfer completes rather than tracking decompressed bytes as they stream
+struct DecompressMiddleware;
+#[surf::utils::async_trait]
+impl surf::middleware::Middleware for DecompressMiddleware {
+    async fn handle(
+        &self,
+        mut req: surf::Request,
+        client: surf::Client,
+        next: surf::middleware::Next<'_>,
+    ) -> surf::Result<surf::Response> {
+        req.insert_header("Accept-Encoding", "gzip, br");
+
+        let mut res = next.run(req, client).await?;
+        if let Some(encoding) = res.header("Content-Encoding").map(|h| h.as_str().to_owned()) {
+            let body = res.take_body().into_bytes().await?;
+            match decode_body(&encoding, body).await {
+                Ok(decoded) => {
+                    res.set_body(decoded);
+                    res.remove_header("Content-Encoding");
+                }
+                Err(body) => {
+                    debug!("{}: could not decode, leaving body as-is", encoding);
+                    res.set_body(body);
+                }
+            }
+        }
+        Ok(res)
+    }
+}
+
+/// best-effort decompression: on an unknown or malformed encoding, hands the raw body back via `Err`
+async fn decode_body(encoding: &str, body: Vec<u8>) -> Result<Vec<u8>, Vec<u8>> {
+    use async_compression::futures::bufread::{BrotliDecoder, GzipDecoder};
+    use futures::{io::BufReader, AsyncReadExt};
+
+    let mut decoded = Vec::new();
+    let ok = match encoding.to_ascii_lowercase().as_str() {
+        "gzip" | "x-gzip" => GzipDecoder::new(BufReader::new(body.as_slice()))
+            .read_to_end(&mut decoded)
+            .await
+            .is_ok(),
+        "br" => BrotliDecoder::new(BufReader::new(body.as_slice()))
+            .read_to_end(&mut decoded)
+            .await
+            .is_ok(),
+        _ => false,
+    };
+    if ok {
+        Ok(decoded)
+    } else {
+        Err(body)
     }
 }
 
 /// Theia plugins HTTP API
 #[derive(Clone)]
 struct TheiaPluginAPI {
+    client: surf::Client,
     prefix: String,
     suffix: String,
     version: Vec<String>,
@@ -58,47 +280,83 @@ impl TheiaPluginAPI {
     fn new<T: AsRef<str>>(regular: T, version: T, download: T) -> Self {
         let mut split = regular.as_ref().splitn(2, "$$");
         Self {
+            client: build_client(),
             prefix: split.next().unwrap_or_default().to_owned(),
             suffix: split.next().unwrap_or_default().to_owned(),
             version: version.as_ref().split('.').map(|x| x.into()).collect(),
             download: download.as_ref().split('.').map(|x| x.into()).collect(),
         }
     }
-    async fn get_version<T: AsRef<str>>(&self, name: T) -> Result<(Version, String), String> {
+    async fn get_version<T: AsRef<str>>(&self, name: T) -> Result<Vec<(Version, String)>, String> {
         let url = format!("{}{}{}", self.prefix, name.as_ref(), self.suffix);
-        surf::get(&url)
+        self.client
+            .get(&url)
             .recv_bytes()
             .await
             .map_err(|e| e.to_string())
             .and_then(|request| self.parse_json(&request))
             .map_err(|e| format!("{}: {}", url, e))
     }
-    fn parse_json(&self, body: &[u8]) -> Result<(Version, String), String> {
+    fn parse_json(&self, body: &[u8]) -> Result<Vec<(Version, String)>, String> {
         let json: serde_json::Value = serde_json::from_slice(body).map_err(|e| e.to_string())?;
-        let version = self.search_version(&json).ok_or("not find version")?;
-        let version = version.parse().map_err(|e| format!("version error, {}", e))?;
-        let download = self.search_download(&json).ok_or("not find download")?;
-        Ok((version, download.to_owned()))
-    }
-    fn search_version<'t>(&self, json: &'t serde_json::Value) -> Option<&'t str> {
-        let mut version = json;
-        for item in self.version.iter() {
-            version = version.get(item)?;
-            if version.is_array() {
-                version = version.get(0)?;
-            }
+        let versions = self.search_version(&json);
+        let downloads = self.search_download(&json);
+        if versions.is_empty() {
+            return Err("not find version".into());
+        }
+        if downloads.is_empty() {
+            return Err("not find download".into());
+        }
+        if versions.len() != downloads.len() {
+            return Err(format!(
+                "version/download count mismatch, {} versions vs {} downloads",
+                versions.len(),
+                downloads.len()
+            ));
         }
-        version.as_str()
+        versions
+            .into_iter()
+            .zip(downloads.into_iter())
+            .map(|(version, download)| {
+                version
+                    .parse()
+                    .map(|version| (version, download.to_owned()))
+                    .map_err(|e| format!("version error, {}", e))
+            })
+            .collect()
     }
-    fn search_download<'t>(&self, json: &'t serde_json::Value) -> Option<&'t str> {
-        let mut download = json;
-        for item in self.download.iter() {
-            download = download.get(item)?;
-            if download.is_array() {
-                download = download.get(0)?;
-            }
+    /// walk `path` through `json`, diving into every element of an array instead of only index 0
+    fn search_path<'t>(&self, json: &'t serde_json::Value, path: &[String]) -> Vec<&'t str> {
+        match path.split_first() {
+            None => json.as_str().into_iter().collect(),
+            Some((item, rest)) => match json.get(item) {
+                Some(serde_json::Value::Array(array)) => array.iter().flat_map(|item| self.search_path(item, rest)).collect(),
+                Some(node) => self.search_path(node, rest),
+                None => Vec::new(),
+            },
+        }
+    }
+    fn search_version<'t>(&self, json: &'t serde_json::Value) -> Vec<&'t str> {
+        self.search_path(json, &self.version)
+    }
+    fn search_download<'t>(&self, json: &'t serde_json::Value) -> Vec<&'t str> {
+        self.search_path(json, &self.download)
+    }
+}
+
+/// archive format a download was published in, sniffed from its magic bytes
+enum ArchiveKind {
+    Zip,
+    Gzip,
+}
+impl ArchiveKind {
+    fn detect(data: &[u8]) -> Self {
+        if data.starts_with(&[0x1f, 0x8b]) {
+            ArchiveKind::Gzip
+        } else {
+            // `PK\x03\x04`, and also the fallback for anything else
+            ArchiveKind::Zip
         }
-        download.as_str()
     }
 }
 
@@ -112,17 +370,20 @@ impl TheiaPluginLCL {
             directory: directory.as_ref().into(),
         }
     }
-    pub async fn get_version<T: AsRef<str>>(&self, name: T) -> Result<Version, String> {
+    /// `Ok(None)` when the plugin has no `extension.vsixmanifest`, i.e. it is not installed
+    pub async fn get_version<T: AsRef<str>>(&self, name: T) -> Result<Option<Version>, String> {
         let path = self.directory.join(name.as_ref()).join("extension.vsixmanifest");
-        fs::read_to_string(&path)
-            .await
-            .map_err(|e| format!("read vsixmanifest, {:?}", e))
-            .and_then(|content| {
-                let reader = quick_xml::Reader::from_str(&content);
-                self.search_version(reader).ok_or_else(|| "not find version".into())
-            })
-            .and_then(|version| version.parse().map_err(|e| format!("version error, {}", e)))
-            .map_err(|e| format!("{:?}: {}", path, e))
+        let content = match fs::read_to_string(&path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("{:?}: read vsixmanifest, {}", path, e)),
+        };
+        let reader = quick_xml::Reader::from_str(&content);
+        self.search_version(reader)
+            .ok_or_else(|| format!("{:?}: not find version", path))?
+            .parse()
+            .map(Some)
+            .map_err(|e| format!("{:?}: version error, {}", path, e))
     }
     fn search_version<B: BufRead>(&self, mut reader: quick_xml::Reader<B>) -> Option<String> {
         let mut buffer = Vec::new();
@@ -145,28 +406,119 @@ impl TheiaPluginLCL {
         }
         None
     }
+    /// remove the installed directory for `name`, if present
+    async fn remove<T: AsRef<str>>(&self, name: T) -> Result<(), String> {
+        let target = self.directory.join(name.as_ref());
+        match fs::remove_dir_all(&target).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(format!("{:?}: remove dir, {}", target, e)),
+        }
+    }
+    /// list the plugin directory names currently installed under `target`
+    async fn installed(&self) -> Result<Vec<String>, String> {
+        let mut entries = fs::read_dir(&self.directory)
+            .await
+            .map_err(|e| format!("{:?}: read dir, {}", self.directory, e))?;
+        let mut names = vec![];
+        while let Some(entry) = entries.next().await {
+            let entry = entry.map_err(|e| e.to_string())?;
+            if entry.file_type().await.map(|t| t.is_dir()).unwrap_or(false) {
+                if let Some(name) = entry.file_name().to_str() {
+                    names.push(name.to_owned());
+                }
+            }
+        }
+        Ok(names)
+    }
     /// decompress from bytes::Bytes, create or rewrite file in target
-    fn installing<T: AsRef<str>>(&self, name: T, data: &[u8]) -> Result<(), String> {
-        use zip::ZipArchive;
-
+    fn installing<T: AsRef<str>>(&self, name: T, data: &[u8], progress: Option<&ProgressBar>) -> Result<(), String> {
         let target = self.directory.join(name.as_ref());
-        let reader = io::Cursor::new(data);
 
-        ZipArchive::new(reader)
+        match ArchiveKind::detect(data) {
+            ArchiveKind::Zip => self.installing_zip(data, &target, progress),
+            ArchiveKind::Gzip => self.installing_tar_gz(data, &target, progress),
+        }
+        .map_err(|e| format!("{:?}: {}", target, e))
+    }
+    /// join `target` and an archive entry's path, rejecting entries that would escape
+    /// `target` (a "zip-slip"/"tar-slip" entry such as `../../../.bashrc` or an absolute path)
+    fn safe_join<T: AsRef<Path>, E: AsRef<Path>>(target: T, entry_path: E) -> Result<PathBuf, String> {
+        let target = target.as_ref();
+        let entry_path = entry_path.as_ref();
+        if entry_path.components().any(|c| !matches!(c, std::path::Component::Normal(_))) {
+            return Err(format!("{:?}: unsafe archive entry path", entry_path));
+        }
+        Ok(target.join(entry_path))
+    }
+    fn installing_zip<T: AsRef<Path>>(&self, data: &[u8], target: T, progress: Option<&ProgressBar>) -> Result<(), String> {
+        use zip::ZipArchive;
+
+        ZipArchive::new(io::Cursor::new(data))
             .map_err(|e| format!("read zip archive, {}", e))
-            .and_then(|archive| self.savefile(archive, &target))
-            .map_err(|e| format!("{:?}: {}", target, e))
+            .and_then(|archive| self.savefile(archive, target, progress))
+    }
+    fn installing_tar_gz<T: AsRef<Path>>(&self, data: &[u8], target: T, progress: Option<&ProgressBar>) -> Result<(), String> {
+        use flate2::read::GzDecoder;
+        use tar::Archive;
+
+        let target = target.as_ref();
+        if let Some(bar) = progress {
+            // tar has no up-front entry count like zip's central directory, so count by
+            // walking a throwaway pass over the (already in-memory) archive first
+            let count = Archive::new(GzDecoder::new(io::Cursor::new(data)))
+                .entries()
+                .map_err(|e| format!("read tar.gz archive, {}", e))?
+                .count();
+            bar.set_length(count as u64);
+            bar.set_position(0);
+            bar.set_message("extracting");
+        }
+
+        let mut archive = Archive::new(GzDecoder::new(io::Cursor::new(data)));
+        for entry in archive.entries().map_err(|e| format!("read tar.gz archive, {}", e))? {
+            let mut entry = entry.map_err(|e| format!("read tar.gz entry, {}", e))?;
+            if entry.header().entry_type().is_file() {
+                let file_path = Self::safe_join(target, entry.path().map_err(|e| format!("entry path, {}", e))?)?;
+                // Create parent dir
+                file_path.parent().and_then(|x| std::fs::create_dir_all(x).ok());
+                // Write file, preserving the tar's unix mode
+                let mut outfile = std::fs::File::create(&file_path).map_err(|e| format!("create file, {}", e))?;
+                io::copy(&mut entry, &mut outfile).map_err(|e| format!("write file, {}", e))?;
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::PermissionsExt;
+                    if let Ok(mode) = entry.header().mode() {
+                        std::fs::set_permissions(&file_path, fs::Permissions::from_mode(mode))
+                            .map_err(|e| format!("set permission, {}", e))?;
+                    }
+                }
+            }
+            if let Some(bar) = progress {
+                bar.inc(1);
+            }
+        }
+        if let Some(bar) = progress {
+            bar.finish_with_message("done");
+        }
+        Ok(())
     }
     fn savefile<Z: Read + Seek, T: AsRef<Path>>(
         &self,
         mut archive: zip::ZipArchive<Z>,
         target: T,
+        progress: Option<&ProgressBar>,
     ) -> Result<(), String> {
         let target = target.as_ref();
+        if let Some(bar) = progress {
+            bar.set_length(archive.len() as u64);
+            bar.set_position(0);
+            bar.set_message("extracting");
+        }
         for i in 0..archive.len() {
             if let Ok(mut file) = archive.by_index(i) {
                 if file.is_file() {
-                    let file_path = target.join(file.name());
+                    let file_path = Self::safe_join(target, file.name())?;
                     // Create parent dir
                     file_path.parent().and_then(|x| std::fs::create_dir_all(x).ok());
                     // Write file
@@ -183,35 +535,85 @@ impl TheiaPluginLCL {
                     }
                 }
             }
+            if let Some(bar) = progress {
+                bar.inc(1);
+            }
+        }
+        if let Some(bar) = progress {
+            bar.finish_with_message("done");
         }
         Ok(())
     }
 }
 
-#[derive(Debug, Eq, PartialEq, Ord, PartialOrd)]
-pub struct Version {
-    major: u32,
-    minor: u32,
-    patch: u32,
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Version(semver::Version);
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        self.0.cmp(&other.0)
+    }
+}
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
 }
 impl fmt::Display for Version {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+        write!(f, "{}", self.0)
     }
 }
 impl FromStr for Version {
-    type Err = std::num::ParseIntError;
+    type Err = semver::SemVerError;
 
+    /// registries often publish bare `major.minor` or non-digit-prefixed major parts
+    /// (`v1.2.3`), so fall back to a lenient parse before giving up
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut iter = s.split('.');
-        let (major, minor, patch) = match (iter.next(), iter.next(), iter.next()) {
-            (Some(major), Some(minor), Some(patch)) => {
-                let major = major.chars().filter(|x| x.is_ascii_digit()).collect::<String>();
-                (major.parse()?, minor.parse()?, patch.parse()?)
-            }
-            _ => (0, 0, 0),
-        };
-        Ok(Self { major, minor, patch })
+        semver::Version::parse(s).or_else(|e| {
+            let mut parts = s.splitn(3, '.');
+            let major = parts
+                .next()
+                .unwrap_or_default()
+                .chars()
+                .filter(|x| x.is_ascii_digit())
+                .collect::<String>();
+            let minor = parts.next().unwrap_or("0");
+            let patch = parts.next().unwrap_or("0");
+            semver::Version::parse(&format!("{}.{}.{}", major, minor, patch)).map_err(|_| e)
+        }).map(Self)
+    }
+}
+
+/// how a plugin's version should be selected out of the candidates a registry publishes
+#[derive(Debug, Clone)]
+pub enum Requirement {
+    /// always take the highest published version
+    Latest,
+    /// take the highest published version satisfying a semver requirement
+    Req(semver::VersionReq),
+}
+impl Requirement {
+    fn matches(&self, version: &Version) -> bool {
+        match self {
+            Requirement::Latest => true,
+            Requirement::Req(req) => req.matches(&version.0),
+        }
+    }
+}
+impl FromStr for Requirement {
+    type Err = semver::ReqParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("latest") {
+            Ok(Requirement::Latest)
+        } else {
+            semver::VersionReq::parse(s).map(Requirement::Req)
+        }
+    }
+}
+impl Default for Requirement {
+    fn default() -> Self {
+        Requirement::Latest
     }
 }
 
@@ -233,4 +635,79 @@ mod test {
             last = next;
         }
     }
+    #[test]
+    fn requirement_from_str() {
+        assert!(matches!(Requirement::from_str("latest").unwrap(), Requirement::Latest));
+        assert!(matches!(Requirement::from_str("LATEST").unwrap(), Requirement::Latest));
+
+        let require = Requirement::from_str(">=1.2.0, <2.0.0").unwrap();
+        assert!(require.matches(&Version::from_str("1.5.0").unwrap()));
+        assert!(!require.matches(&Version::from_str("2.0.0").unwrap()));
+
+        assert!(Requirement::from_str("not a requirement").is_err());
+    }
+    #[test]
+    fn search_path_flattens_arrays() {
+        let api = TheiaPluginAPI::new("http://example.com/$$", "versions.version", "versions.dist.tar");
+        let json: serde_json::Value = serde_json::from_str(
+            r#"{"versions": [
+                {"version": "1.0.0", "dist": {"tar": "a.tar"}},
+                {"version": "1.1.0", "dist": {"tar": "b.tar"}}
+            ]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(api.search_version(&json), vec!["1.0.0", "1.1.0"]);
+        assert_eq!(api.search_download(&json), vec!["a.tar", "b.tar"]);
+    }
+    #[test]
+    fn parse_json_rejects_mismatched_version_download_counts() {
+        // one entry is missing its `dist.tar` field, so `search_download` comes back one short
+        // of `search_version` - pairing them positionally would silently mis-pair every
+        // subsequent version with the wrong download, so this must error instead
+        let api = TheiaPluginAPI::new("http://example.com/$$", "versions.version", "versions.dist.tar");
+        let body = br#"{"versions": [
+            {"version": "1.0.0", "dist": {"tar": "a.tar"}},
+            {"version": "1.1.0"}
+        ]}"#;
+
+        assert!(api.parse_json(body).is_err());
+    }
+    #[test]
+    fn safe_join_rejects_path_traversal() {
+        assert_eq!(
+            TheiaPluginLCL::safe_join("/target", "a/b.txt").unwrap(),
+            PathBuf::from("/target/a/b.txt")
+        );
+        assert!(TheiaPluginLCL::safe_join("/target", "../../../etc/passwd").is_err());
+        assert!(TheiaPluginLCL::safe_join("/target", "/etc/passwd").is_err());
+    }
+    #[test]
+    fn archive_kind_detect() {
+        assert!(matches!(ArchiveKind::detect(&[0x1f, 0x8b, 0x08, 0x00]), ArchiveKind::Gzip));
+        assert!(matches!(ArchiveKind::detect(b"PK\x03\x04"), ArchiveKind::Zip));
+        assert!(matches!(ArchiveKind::detect(b"not an archive"), ArchiveKind::Zip));
+    }
+    #[test]
+    fn sha256_hex_known_vector() {
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+    #[test]
+    fn decode_body_roundtrips_gzip_and_falls_back_on_unknown_encoding() {
+        use std::io::Write;
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"hello plugin").unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let decoded = async_std::task::block_on(decode_body("gzip", compressed)).unwrap();
+        assert_eq!(decoded, b"hello plugin");
+
+        let raw = b"not compressed".to_vec();
+        let fallback = async_std::task::block_on(decode_body("identity", raw.clone())).unwrap_err();
+        assert_eq!(fallback, raw);
+    }
 }
@@ -5,6 +5,7 @@ use async_std::task;
 use chrono::Local;
 use env_logger::Builder;
 use futures::future;
+use indicatif::{MultiProgress, ProgressBar};
 use log::{debug, info, warn};
 use std::{
     error,
@@ -23,6 +24,32 @@ struct Opt {
     /// theia config dir
     #[structopt(short = "t", long = "target", default_value = "$HOME/.theia/plugins")]
     target: PathBuf,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// upgrade every configured plugin to the version matching its `require` (default)
+    Upgrade,
+    /// install a single configured plugin
+    Install { name: String },
+    /// remove an installed plugin's directory
+    Uninstall { name: String },
+    /// list configured plugins with their installed and latest resolvable versions
+    List,
+    /// delete installed plugin directories no longer present in the config
+    Prune,
+    /// wipe the downloaded-archive cache
+    ClearCache,
+}
+
+/// one plugin entry resolved out of `plugins.toml`
+struct Entry {
+    name: String,
+    plugin: TheiaPlugin,
+    path: String,
+    require: Requirement,
 }
 
 fn main() -> Result<(), Box<dyn error::Error>> {
@@ -50,13 +77,28 @@ fn main() -> Result<(), Box<dyn error::Error>> {
     info!("{:#?}", opt);
 
     // Get plugins configuration information
-    let config = fs::read_to_string(opt.config)?.parse::<toml::Value>()?;
+    let config = fs::read_to_string(&opt.config)?.parse::<toml::Value>()?;
     let config = match config.as_table() {
         Some(x) => x,
         None => return Ok(()),
     };
 
-    let mut future_list = vec![];
+    let entries = parse_entries(config, &opt.target);
+
+    match opt.command.unwrap_or(Command::Upgrade) {
+        Command::Upgrade => task::block_on(upgrade_all(entries)),
+        Command::Install { name } => task::block_on(install_one(entries, &name)),
+        Command::Uninstall { name } => task::block_on(uninstall_one(entries, &name)),
+        Command::List => task::block_on(list(entries)),
+        Command::Prune => task::block_on(prune(entries, &opt.target)),
+        Command::ClearCache => task::block_on(clear_cache(&opt.target)),
+    }
+
+    Ok(())
+}
+
+fn parse_entries(config: &toml::value::Table, target: &PathBuf) -> Vec<Entry> {
+    let mut entries = vec![];
 
     for (domain, table) in config {
         let plugin = match (
@@ -64,7 +106,7 @@ fn main() -> Result<(), Box<dyn error::Error>> {
             table.get("version").and_then(|x| x.as_str()),
             table.get("download").and_then(|x| x.as_str()),
         ) {
-            (Some(regular), Some(version), Some(download)) => TheiaPlugin::new(regular, version, download, &opt.target),
+            (Some(regular), Some(version), Some(download)) => TheiaPlugin::new(regular, version, download, target),
             _ => {
                 warn!("{}: missing information", domain);
                 continue;
@@ -74,35 +116,197 @@ fn main() -> Result<(), Box<dyn error::Error>> {
         let null_table = toml::value::Table::new();
         let download_list = table.get("list").and_then(|x| x.as_table()).unwrap_or(&null_table);
 
-        for (name, path) in download_list
-            .into_iter()
-            .filter_map(|(name, path)| path.as_str().map(|path| (name.to_owned(), path.to_owned())))
-        {
-            future_list.push(task::spawn(upgrade(plugin.clone(), name, path)));
+        for (name, entry) in download_list {
+            let (path, require) = match entry {
+                toml::Value::String(path) => (path.to_owned(), Requirement::Latest),
+                toml::Value::Table(entry) => match entry.get("path").and_then(|x| x.as_str()) {
+                    Some(path) => {
+                        let require = entry
+                            .get("require")
+                            .and_then(|x| x.as_str())
+                            .map(|x| x.parse())
+                            .transpose()
+                            .unwrap_or_else(|e| {
+                                warn!("{}: invalid require, {}", name, e);
+                                None
+                            })
+                            .unwrap_or_default();
+                        (path.to_owned(), require)
+                    }
+                    None => {
+                        warn!("{}: missing path", name);
+                        continue;
+                    }
+                },
+                _ => {
+                    warn!("{}: invalid list entry", name);
+                    continue;
+                }
+            };
+            entries.push(Entry {
+                name: name.to_owned(),
+                plugin: plugin.clone(),
+                path,
+                require,
+            });
+        }
+    }
+
+    entries
+}
+
+/// bars only make sense against an interactive terminal; plain `log` output is the fallback
+fn new_progress() -> Option<MultiProgress> {
+    if atty::is(atty::Stream::Stdout) {
+        Some(MultiProgress::new())
+    } else {
+        None
+    }
+}
+
+fn add_bar(multi: &Option<MultiProgress>, name: &str) -> Option<ProgressBar> {
+    multi.as_ref().map(|multi| {
+        let bar = multi.add(ProgressBar::new(0));
+        bar.set_prefix(name);
+        bar
+    })
+}
+
+async fn upgrade_all(entries: Vec<Entry>) {
+    let multi = new_progress();
+
+    let future_list = entries
+        .into_iter()
+        .map(|entry| {
+            let progress = add_bar(&multi, &entry.name);
+            task::spawn(upgrade(entry.plugin, entry.name, entry.path, entry.require, progress))
+        })
+        .collect::<Vec<_>>();
+
+    // MultiProgress draws from the calling thread, so give it one of its own while tasks run
+    let drawer = multi.map(|multi| std::thread::spawn(move || multi.join()));
+
+    for warn in future::join_all(future_list).await {
+        if let Err(warn) = warn {
+            warn!("{}", warn);
+        }
+    }
+
+    if let Some(drawer) = drawer {
+        let _ = drawer.join();
+    }
+}
+
+async fn install_one(entries: Vec<Entry>, name: &str) {
+    match entries.into_iter().find(|entry| entry.name == name) {
+        Some(entry) => {
+            let multi = new_progress();
+            let progress = add_bar(&multi, &entry.name);
+            let drawer = multi.map(|multi| std::thread::spawn(move || multi.join()));
+
+            if let Err(e) = upgrade(entry.plugin, entry.name, entry.path, entry.require, progress).await {
+                warn!("{}", e);
+            }
+
+            if let Some(drawer) = drawer {
+                let _ = drawer.join();
+            }
         }
+        None => warn!("{}: not found in config", name),
+    }
+}
+
+async fn uninstall_one(entries: Vec<Entry>, name: &str) {
+    match entries.into_iter().find(|entry| entry.name == name) {
+        Some(entry) => match entry.plugin.uninstall(&entry.name).await {
+            Ok(()) => info!("{}: uninstalled", entry.name),
+            Err(e) => warn!("{}: {}", entry.name, e),
+        },
+        None => warn!("{}: not found in config", name),
+    }
+}
+
+async fn list(entries: Vec<Entry>) {
+    for entry in entries {
+        let (installed, last) = future::join(
+            entry.plugin.get_install_info(&entry.name),
+            entry.plugin.get_last_version(entry.path, &entry.require),
+        )
+        .await;
+
+        let installed = match installed {
+            Ok(Some(version)) => version.to_string(),
+            Ok(None) => "-".to_owned(),
+            Err(e) => format!("error: {}", e),
+        };
+        let last = match last {
+            Ok((version, _)) => version.to_string(),
+            Err(e) => format!("error: {}", e),
+        };
+        println!("{}\tinstalled: {}\tlatest: {}", entry.name, installed, last);
     }
+}
+
+async fn prune(entries: Vec<Entry>, target: &PathBuf) {
+    // the installed/cache directories only depend on `--target`, not on any configured entry,
+    // so this must work even when `list` has been emptied out entirely
+    let native = TheiaPlugin::new("", "", "", target);
+    let configured: Vec<&str> = entries.iter().map(|entry| entry.name.as_str()).collect();
+
+    let installed = match native.installed().await {
+        Ok(installed) => installed,
+        Err(e) => {
+            warn!("{}", e);
+            return;
+        }
+    };
 
-    task::block_on(async {
-        for warn in future::join_all(future_list).await {
-            if let Err(warn) = warn {
-                warn!("{}", warn);
+    for name in installed {
+        if !configured.contains(&name.as_str()) {
+            match native.uninstall(&name).await {
+                Ok(()) => info!("{}: pruned", name),
+                Err(e) => warn!("{}: {}", name, e),
             }
         }
-    });
+    }
+}
 
-    Ok(())
+async fn clear_cache(target: &PathBuf) {
+    match TheiaPlugin::new("", "", "", target).clear_cache().await {
+        Ok(()) => info!("cache cleared"),
+        Err(e) => warn!("{}", e),
+    }
 }
 
-async fn upgrade(plugin: TheiaPlugin, name: String, path: String) -> Result<(), String> {
+async fn upgrade(
+    plugin: TheiaPlugin,
+    name: String,
+    path: String,
+    require: Requirement,
+    progress: Option<ProgressBar>,
+) -> Result<(), String> {
     let prefix = format!("{}: ", name);
 
-    let (version_old, version_new) = future::join(plugin.get_install_info(&name), plugin.get_last_version(path)).await;
+    let (version_old, version_new) = future::join(plugin.get_install_info(&name), plugin.get_last_version(path, &require)).await;
 
-    let version_old = version_old.map_err(|e| prefix.clone() + &e)?;
-    let (version_new, download) = version_new.map_err(|e| prefix.clone() + &e)?;
+    let version_old = version_old.map_err(|e| {
+        if let Some(bar) = &progress {
+            bar.finish_and_clear();
+        }
+        prefix.clone() + &e
+    })?;
+    let (version_new, download) = version_new.map_err(|e| {
+        if let Some(bar) = &progress {
+            bar.finish_and_clear();
+        }
+        prefix.clone() + &e
+    })?;
 
     if version_old.as_ref() == Some(&version_new) {
         debug!("{}latest {} is installed", prefix, version_new);
+        if let Some(bar) = &progress {
+            bar.finish_and_clear();
+        }
         return Ok(());
     }
 
@@ -114,5 +318,8 @@ async fn upgrade(plugin: TheiaPlugin, name: String, path: String) -> Result<(),
         download
     );
 
-    plugin.upgrade(name, download).await.map_err(|e| prefix.clone() + &e)
+    plugin
+        .upgrade(name, &version_new, download, progress)
+        .await
+        .map_err(|e| prefix.clone() + &e)
 }